@@ -0,0 +1,251 @@
+//! Parsing and validation of the `triagebot.toml` configuration file that
+//! each onboarded repository may provide.
+//!
+//! Each handler that wants per-repository configuration adds its own
+//! section here, keyed by the `[section]` name in the TOML file. See
+//! `LabelConfig` for an example of a handler-owned configuration schema.
+
+use crate::github::GithubClient;
+use failure::Error;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub label: LabelConfig,
+    #[serde(default)]
+    pub autolabel: AutolabelConfig,
+}
+
+/// Configuration for `crate::handlers::autolabel`, loaded from the
+/// `[autolabel]` section of `triagebot.toml`.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct AutolabelConfig {
+    /// Maps an inline `#hashtag` found in a newly opened issue or PR body to
+    /// the label that should be applied for it. A table is needed (rather
+    /// than using the hashtag text as the label name) because GitHub label
+    /// names often contain characters, such as spaces or `-`, that `\w`
+    /// won't match.
+    #[serde(default)]
+    pub hashtags: std::collections::HashMap<String, String>,
+}
+
+/// Declarative permissions for the `label` command, loaded from the
+/// `[label]` section of a repository's `triagebot.toml`.
+///
+/// This replaces the old hardcoded `C-`/`A-`/`S-`/... prefix rules: each
+/// repository now lists its own label taxonomy and who is allowed to set
+/// it, modeled on the rust-lang team repo's `Permissions` schema.
+///
+/// `allow` is checked before any `team-only` rule, and `team-only` rules are
+/// tried in order: the first one whose pattern matches applies, so a
+/// narrower rule must come before a wider one.
+///
+/// ```toml
+/// [label]
+/// allow = ["C-*", "A-*", "E-*"]
+///
+/// [[label.team-only]]
+/// pattern = "T-compiler"
+/// team = "compiler"
+///
+/// [[label.team-only]]
+/// pattern = "T-*"
+/// ```
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct LabelConfig {
+    /// Glob patterns (an optional trailing `*`) that any user may set.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Glob patterns that only Rust team members may set.
+    #[serde(default, rename = "team-only")]
+    pub team_only: Vec<TeamOnlyLabel>,
+    /// Overrides for the bot's response text, so non-rust-lang projects using triagebot can
+    /// soften or redirect the wording without a recompile.
+    #[serde(default)]
+    pub messages: LabelMessages,
+    /// Opts in to `@triagebot label-block`/`label-grant`, which persist moderation state by
+    /// committing a JSON file to this repository (see `crate::handlers::moderation`). Off by
+    /// default, since writing bot-owned state into a third-party repo is a capability a project
+    /// should explicitly choose rather than gain silently by upgrading the bot.
+    #[serde(default, rename = "moderation-enabled")]
+    pub moderation_enabled: bool,
+}
+
+/// Overridable response templates for `crate::handlers::label`.
+///
+/// Each template supports a subset of the `{label}`, `{team}`, `{comment_url}` and `{error}`
+/// placeholders, substituted verbatim (no escaping) at render time. Unset fields fall back to
+/// the bot's built-in English default.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+pub struct LabelMessages {
+    /// Shown when a non-team-member tries to set a `team-only` label. Supports `{label}`.
+    pub team_only: Option<String>,
+    /// Shown when the team-membership check itself couldn't be completed (e.g. during a GitHub
+    /// outage), rather than returning a definite answer. Supports `{label}`.
+    pub team_only_check_failed: Option<String>,
+    /// Shown when a label is scoped to a specific `team` in `[[label.team-only]]` (e.g. `T-compiler`
+    /// scoped to `"compiler"`), which the bot cannot yet check membership of. Supports `{label}`
+    /// and `{team}`.
+    pub team_scoped_unsupported: Option<String>,
+    /// Shown when a `label` command fails to parse. Supports `{comment_url}` and `{error}`.
+    pub parse_failed: Option<String>,
+}
+
+const DEFAULT_TEAM_ONLY: &str = "Label {label} can only be set by team members";
+const DEFAULT_TEAM_ONLY_CHECK_FAILED: &str = "Label {label} can only be set by team members; \
+    we were unable to check if you are a team member.";
+const DEFAULT_TEAM_SCOPED_UNSUPPORTED: &str = "Label {label} is restricted to the {team} team, \
+    and this bot cannot yet verify membership in a specific team; ask a {team} team member to \
+    apply it for you.";
+const DEFAULT_PARSE_FAILED: &str = "Parsing label command in [comment]({comment_url}) failed: {error}";
+
+impl LabelMessages {
+    pub fn render_team_only(&self, label: &str) -> String {
+        render(self.team_only.as_deref().unwrap_or(DEFAULT_TEAM_ONLY), &[("{label}", label)])
+    }
+
+    pub fn render_team_only_check_failed(&self, label: &str) -> String {
+        render(
+            self.team_only_check_failed
+                .as_deref()
+                .unwrap_or(DEFAULT_TEAM_ONLY_CHECK_FAILED),
+            &[("{label}", label)],
+        )
+    }
+
+    pub fn render_team_scoped_unsupported(&self, label: &str, team: &str) -> String {
+        render(
+            self.team_scoped_unsupported
+                .as_deref()
+                .unwrap_or(DEFAULT_TEAM_SCOPED_UNSUPPORTED),
+            &[("{label}", label), ("{team}", team)],
+        )
+    }
+
+    pub fn render_parse_failed(&self, comment_url: &str, error: &str) -> String {
+        render(
+            self.parse_failed.as_deref().unwrap_or(DEFAULT_PARSE_FAILED),
+            &[("{comment_url}", comment_url), ("{error}", error)],
+        )
+    }
+}
+
+fn render(template: &str, placeholders: &[(&str, &str)]) -> String {
+    let mut out = template.to_string();
+    for (key, value) in placeholders {
+        out = out.replace(key, value);
+    }
+    out
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TeamOnlyLabel {
+    /// Glob pattern (an optional trailing `*`) this rule applies to.
+    pub pattern: String,
+    /// Restricts this rule to members of a single team (e.g. `"compiler"` for `T-compiler`).
+    /// **Not yet enforced as a narrower permission**: `GithubClient` has no way to check
+    /// membership in a single named team, only overall Rust team membership, so `check_filter`
+    /// fails closed on this case (see `handlers::label::check_filter`) rather than letting any
+    /// Rust team member set the label, until a per-team membership query exists.
+    pub team: Option<String>,
+}
+
+impl LabelConfig {
+    /// Looks up the rule that governs `label`, if any.
+    pub fn rule_for(&self, label: &str) -> LabelRule<'_> {
+        if self.allow.iter().any(|pattern| pattern_matches(pattern, label)) {
+            return LabelRule::Allowed;
+        }
+        match self
+            .team_only
+            .iter()
+            .find(|rule| pattern_matches(&rule.pattern, label))
+        {
+            Some(rule) => LabelRule::TeamOnly(rule.team.as_deref()),
+            None => LabelRule::Unrecognized,
+        }
+    }
+
+    /// Rejects configurations that reference labels not present on the
+    /// project. Wildcard patterns (ending in `*`) are not checked, since
+    /// they are prefixes rather than concrete labels.
+    pub fn validate(&self, known_labels: &[String]) -> Result<(), Error> {
+        let patterns = self
+            .allow
+            .iter()
+            .chain(self.team_only.iter().map(|rule| &rule.pattern));
+        for pattern in patterns {
+            if pattern.ends_with('*') {
+                continue;
+            }
+            if !known_labels.iter().any(|label| label == pattern) {
+                failure::bail!(
+                    "triagebot.toml [label] section references unknown label `{}`",
+                    pattern
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of looking up a label against a `LabelConfig`.
+pub enum LabelRule<'a> {
+    /// Anyone may set this label.
+    Allowed,
+    /// If `None`, any Rust team member may set this label. If it names a
+    /// team, `handlers::label::check_filter` refuses the label to every user
+    /// without a moderation grant, since membership of a single team is not
+    /// checked.
+    TeamOnly(Option<&'a str>),
+    /// The label is not mentioned anywhere in the config; treated as
+    /// denied.
+    Unrecognized,
+}
+
+/// Matches `label` against `pattern`, where a trailing `*` in `pattern`
+/// matches any suffix.
+pub fn pattern_matches(pattern: &str, label: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => label.starts_with(prefix),
+        None => pattern == label,
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Caches `get_config`'s result per repo so that fetching `triagebot.toml`, fetching the
+    /// repo's label list, and validating the two against each other happens once rather than on
+    /// every `IssueComment`/`Issue` event the bot receives. The cache is never invalidated in
+    /// this process, so a live `triagebot.toml` edit needs a bot restart to take effect; that's
+    /// the same staleness window the "validate at startup" request asked for, just keyed to
+    /// first-use instead of process-start since the bot has no fixed list of repos to warm.
+    static ref CONFIG_CACHE: RwLock<HashMap<String, Config>> = RwLock::new(HashMap::new());
+}
+
+/// Fetches and validates the `triagebot.toml` for `repo`, if present.
+/// Falls back to an empty (deny-everything-by-default) configuration when
+/// the repository has none.
+///
+/// See `CONFIG_CACHE`: this only talks to GitHub and validates the first time `repo` is seen. A
+/// config that fails validation is still cached and returned (with the error logged, not
+/// propagated) so that one bad `triagebot.toml` entry doesn't take down every other command on
+/// every event for the rest of the process, including `@triagebot labels` itself.
+pub fn get_config(client: &GithubClient, repo: &str) -> Result<Config, Error> {
+    if let Some(config) = CONFIG_CACHE.read().unwrap().get(repo) {
+        return Ok(config.clone());
+    }
+
+    let config: Config = match client.raw_file(repo, "triagebot.toml")? {
+        Some(contents) => toml::from_slice(&contents)?,
+        None => Config::default(),
+    };
+    let known_labels = client.list_labels(repo)?;
+    if let Err(err) = config.label.validate(&known_labels) {
+        eprintln!("triagebot.toml for {} failed validation: {}", repo, err);
+    }
+
+    CONFIG_CACHE.write().unwrap().insert(repo.to_string(), config.clone());
+    Ok(config)
+}
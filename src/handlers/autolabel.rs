@@ -0,0 +1,111 @@
+//! Purpose: Automatically label newly opened issues and PRs based on inline `#hashtag` mentions
+//! in the body, e.g. a reporter writing `#regression #ICE` gets those labels applied without
+//! needing to know the bot's `label` command syntax.
+//!
+//! Hashtags are mapped to label names through the `[autolabel]` section of `triagebot.toml`
+//! (see `crate::config::AutolabelConfig`), since GitHub label names often contain characters
+//! that `\w` won't match. Only labels covered by the `allow` list of the `[label]` section are
+//! applied, so a hashtag can't be used to sneak in a team-only label, and an issue's author still
+//! can't get a label they've been `crate::handlers::moderation`-blocked from applied to their own
+//! issue just by mentioning its hashtag. This is stricter than the explicit `label` command:
+//! team-only and unconfigured labels are never applied from a hashtag, even when the author is a
+//! team member or holds a moderation grant for the label.
+
+use crate::{
+    config::{self, LabelRule},
+    github::{self, GithubClient},
+    handlers::moderation::{self, ModerationVerdict},
+    registry::{Event, Handler},
+};
+use failure::Error;
+use regex::Regex;
+
+lazy_static::lazy_static! {
+    static ref HASHTAG: Regex = Regex::new(r"(?:^|\b|\s|>|\n)#(\w+)").unwrap();
+    static ref HTML_TAG: Regex = Regex::new(r"<[^>]*>").unwrap();
+    static ref FENCED_CODE_BLOCK: Regex = Regex::new(r"(?s)```.*?```").unwrap();
+}
+
+pub struct AutolabelHandler {
+    pub client: GithubClient,
+}
+
+impl Handler for AutolabelHandler {
+    fn handle_event(&self, event: &Event) -> Result<(), Error> {
+        let e = if let Event::Issue(e) = event {
+            e
+        } else {
+            // not interested in other events
+            return Ok(());
+        };
+
+        if e.action != github::IssuesAction::Opened {
+            return Ok(());
+        }
+
+        let repo = &e.issue.repository.full_name;
+        let config = config::get_config(&self.client, repo)?;
+        if config.autolabel.hashtags.is_empty() {
+            return Ok(());
+        }
+
+        let existing: Vec<&str> = e.issue.labels().iter().map(|l| l.name.as_str()).collect();
+        let mut to_add = Vec::new();
+        for tag in extract_hashtags(&e.issue.body) {
+            let label = match config.autolabel.hashtags.get(&tag) {
+                Some(label) => label,
+                None => continue,
+            };
+            if existing.contains(&label.as_str()) || to_add.contains(label) {
+                continue;
+            }
+            match config.label.rule_for(label) {
+                LabelRule::Allowed => {}
+                LabelRule::TeamOnly(_) | LabelRule::Unrecognized => continue,
+            }
+            to_add.push(label.clone());
+        }
+
+        if to_add.is_empty() {
+            return Ok(());
+        }
+
+        // Only fetch moderation state once there's at least one candidate label to check it
+        // against, so an issue whose body has no (or no matching) hashtags doesn't cost an extra
+        // GitHub API call.
+        let moderation = moderation::load(&self.client, repo)?;
+        to_add.retain(|label| {
+            !matches!(
+                moderation.verdict(&e.issue.user.login, label),
+                ModerationVerdict::Blocked
+            )
+        });
+
+        if to_add.is_empty() {
+            return Ok(());
+        }
+
+        let mut labels = e.issue.labels().to_owned();
+        labels.extend(to_add.into_iter().map(|name| github::Label { name }));
+        e.issue.set_labels(&self.client, labels)?;
+
+        Ok(())
+    }
+}
+
+/// Strips fenced code blocks and HTML from `body`, then collects the distinct `#hashtag` tokens
+/// that remain, in lowercase.
+fn extract_hashtags(body: &str) -> Vec<String> {
+    let body = FENCED_CODE_BLOCK.replace_all(body, "");
+    let body = body.replace("<br/>", " ").replace("</p>", " ");
+    let body = HTML_TAG.replace_all(&body, "");
+
+    let mut tags = Vec::new();
+    for capture in HASHTAG.captures_iter(&body) {
+        let tag = capture[1].to_lowercase();
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
@@ -0,0 +1,43 @@
+//! Typed errors shared by command handlers.
+//!
+//! `handle_event` used to report everything through `failure::bail!` with ad-hoc string
+//! messages, which left the registry unable to tell a user mistake (bad syntax, insufficient
+//! permissions) apart from an infrastructure hiccup (a GitHub API timeout). The registry needs
+//! that distinction: user errors are worth an issue comment, infrastructure errors are worth a
+//! retry and a log line, not a comment on someone's issue.
+
+use failure::Fail;
+
+#[derive(Debug, Fail)]
+pub enum HandlerError {
+    #[fail(display = "failed to parse command: {}", _0)]
+    ParseFailed(String),
+    #[fail(display = "permission denied: {}", _0)]
+    PermissionDenied(String),
+    #[fail(display = "GitHub API call timed out after retrying")]
+    ApiTimeout,
+    #[fail(display = "label `{}` does not exist on this repository", _0)]
+    LabelNotFound(String),
+    #[fail(display = "could not verify team membership: {}", _0)]
+    MembershipCheckFailed(String),
+}
+
+impl HandlerError {
+    /// Whether this error is the user's fault (bad input, insufficient permissions) and should
+    /// be surfaced as an issue comment, as opposed to an infrastructure failure that should be
+    /// retried/logged instead.
+    ///
+    /// `MembershipCheckFailed` counts as a user error even though it is triggered by a GitHub
+    /// API hiccup: from the commenter's point of view it's indistinguishable from a permission
+    /// denial (their label command didn't go through), so it gets the same comment-then-`Ok(())`
+    /// treatment rather than being retried/logged as pure infrastructure failure.
+    pub fn is_user_error(&self) -> bool {
+        match self {
+            HandlerError::ParseFailed(_)
+            | HandlerError::PermissionDenied(_)
+            | HandlerError::LabelNotFound(_)
+            | HandlerError::MembershipCheckFailed(_) => true,
+            HandlerError::ApiTimeout => false,
+        }
+    }
+}
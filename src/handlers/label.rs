@@ -3,19 +3,54 @@
 //! Labels are checked against the labels in the project; the bot does not support creating new
 //! labels.
 //!
+//! Who may set which label is governed by the `[label]` section of the target repository's
+//! `triagebot.toml`, see `crate::config::LabelConfig`.
+//!
 //! Parsing is done in the `parser::command::label` module.
 //!
 //! If the command was successful, there will be no feedback beyond the label change to reduce
 //! notification noise.
+//!
+//! `@triagebot labels` is a separate discovery command (see `is_labels_query` and
+//! `labels_help_comment`) that posts a Markdown summary of the project's labels, since otherwise
+//! first-time contributors have no way to learn the label taxonomy short of reading
+//! `triagebot.toml` themselves.
+//!
+//! `@triagebot label-block @user [pattern...]` and `@triagebot label-grant @user pattern` manage
+//! the per-user moderation state in `crate::handlers::moderation`, consulted by `check_filter`
+//! before the usual prefix/team rules. Both are team-only commands.
 
 use crate::{
+    config::{self, LabelConfig, LabelRule},
     github::{self, GithubClient},
+    handlers::{
+        error::HandlerError,
+        moderation::{self, ModerationState, ModerationVerdict},
+    },
     interactions::ErrorComment,
     registry::{Event, Handler},
+    retry,
 };
 use failure::Error;
 use parser::command::label::{LabelCommand, LabelDelta};
 use parser::command::{Command, Input};
+use regex::Regex;
+use std::collections::BTreeMap;
+
+// Compiled once, like `handlers::autolabel`'s `HASHTAG`/`HTML_TAG`/`FENCED_CODE_BLOCK`, rather
+// than per `IssueComment` webhook. The bot's username can't be baked into the pattern at compile
+// time, so these match any `@name` prefix and `is_labels_query`/`parse_moderation_command` check
+// it against `self.client.username()` themselves.
+lazy_static::lazy_static! {
+    static ref LABELS_QUERY: Regex = Regex::new(r"(?i)@(\S+)\s+labels\b").unwrap();
+    // The trailing pattern-capture group is anchored to the rest of the *line* (`[^\n]*`, not
+    // `\s` which also matches newlines) so that prose in a later paragraph of the same comment
+    // isn't swept up and persisted into the moderation JSON as a glob pattern.
+    static ref BLOCK_COMMAND: Regex =
+        Regex::new(r"(?i)@(\S+)\s+label-block\s+@(\S+)([^\n]*)").unwrap();
+    static ref GRANT_COMMAND: Regex =
+        Regex::new(r"(?i)@(\S+)\s+label-grant\s+@(\S+)\s+(\S+)").unwrap();
+}
 
 pub struct LabelHandler {
     pub client: GithubClient,
@@ -33,23 +68,96 @@ impl Handler for LabelHandler {
 
         let mut issue_labels = event.issue.labels().to_owned();
 
+        let repo = &event.issue.repository.full_name;
+        let config = config::get_config(&self.client, repo)?.label;
+
+        if is_labels_query(&event.comment.body, self.client.username()) {
+            let known_labels = self.client.list_labels(repo)?;
+            event
+                .issue
+                .post_comment(&self.client, labels_help_comment(&known_labels, &config))?;
+            return Ok(());
+        }
+
+        if let Some(command) = parse_moderation_command(&event.comment.body, self.client.username()) {
+            if !config.moderation_enabled {
+                ErrorComment::new(
+                    &event.issue,
+                    "Label moderation is not enabled for this repository; set \
+                     `moderation-enabled = true` under `[label]` in `triagebot.toml` to opt in."
+                        .to_string(),
+                )
+                .post(&self.client)?;
+                return Ok(());
+            }
+
+            // Retry like `check_filter` does: a failed membership check is a transient API
+            // hiccup, not a definitive "not a team member", and shouldn't silently lock out a
+            // legitimate team member during a GitHub outage.
+            match retry::with_retry(
+                || event.comment.user.is_team_member(&self.client),
+                retry::is_transient_github_error,
+            ) {
+                Ok(true) => {}
+                Ok(false) => {
+                    ErrorComment::new(
+                        &event.issue,
+                        "Only Rust team members may manage label moderation.".to_string(),
+                    )
+                    .post(&self.client)?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    if !retry::is_transient_github_error(&err) {
+                        return Err(err);
+                    }
+                    eprintln!(
+                        "team membership check for label moderation on issue #{} timed out after retrying: {:?}",
+                        event.issue.number, err
+                    );
+                    return Err(HandlerError::ApiTimeout.into());
+                }
+            }
+
+            let mut state = moderation::load(&self.client, repo)?;
+            let summary = match command {
+                ModerationCommand::Block { user, patterns } => {
+                    let summary = if patterns.is_empty() {
+                        format!("@{} is now blocked from modifying labels.", user)
+                    } else {
+                        format!(
+                            "@{} is now blocked from modifying labels matching: {}",
+                            user,
+                            patterns.join(", ")
+                        )
+                    };
+                    state.block(&user, patterns);
+                    summary
+                }
+                ModerationCommand::Grant { user, pattern } => {
+                    state.grant(&user, pattern.clone());
+                    format!("@{} may now set labels matching `{}`.", user, pattern)
+                }
+            };
+            moderation::save(&self.client, repo, &state)?;
+            event.issue.post_comment(&self.client, summary)?;
+            return Ok(());
+        }
+
+        let moderation = moderation::load(&self.client, repo)?;
+
         let mut input = Input::new(&event.comment.body, self.client.username());
         let deltas = match input.parse_command() {
             Command::Label(Ok(LabelCommand(deltas))) => deltas,
             Command::Label(Err(err)) => {
                 ErrorComment::new(
                     &event.issue,
-                    format!(
-                        "Parsing label command in [comment]({}) failed: {}",
-                        event.comment.html_url, err
-                    ),
+                    config
+                        .messages
+                        .render_parse_failed(&event.comment.html_url, &err.to_string()),
                 )
                 .post(&self.client)?;
-                failure::bail!(
-                    "label parsing failed for issue #{}, error: {:?}",
-                    event.issue.number,
-                    err
-                );
+                return Err(HandlerError::ParseFailed(err.to_string()).into());
             }
             _ => return Ok(()),
         };
@@ -57,9 +165,31 @@ impl Handler for LabelHandler {
         let mut changed = false;
         for delta in &deltas {
             let name = delta.label().as_str();
-            if let Err(msg) = check_filter(name, &event.comment.user, &self.client) {
-                ErrorComment::new(&event.issue, msg).post(&self.client)?;
-                return Ok(());
+            if let Err(err) = check_filter(
+                name,
+                &event.comment.user,
+                &self.client,
+                &config,
+                &moderation,
+            ) {
+                if err.is_user_error() {
+                    let message = match &err {
+                        HandlerError::PermissionDenied(msg)
+                        | HandlerError::MembershipCheckFailed(msg) => msg.clone(),
+                        HandlerError::LabelNotFound(label) => format!(
+                            "Label {} is not recognized by this project's label configuration",
+                            label
+                        ),
+                        HandlerError::ParseFailed(_) | HandlerError::ApiTimeout => unreachable!(),
+                    };
+                    ErrorComment::new(&event.issue, message).post(&self.client)?;
+                    return Ok(());
+                }
+                eprintln!(
+                    "deferring label command for issue #{}: {}",
+                    event.issue.number, err
+                );
+                return Err(err.into());
             }
             match delta {
                 LabelDelta::Add(label) => {
@@ -80,54 +210,155 @@ impl Handler for LabelHandler {
         }
 
         if changed {
-            event.issue.set_labels(&self.client, issue_labels)?;
+            retry::with_retry(
+                || event.issue.set_labels(&self.client, issue_labels.clone()),
+                retry::is_transient_github_error,
+            )
+            .map_err(|err| {
+                if retry::is_transient_github_error(&err) {
+                    Error::from(HandlerError::ApiTimeout)
+                } else {
+                    err
+                }
+            })?;
         }
 
         Ok(())
     }
 }
 
-fn check_filter(label: &str, user: &github::User, client: &GithubClient) -> Result<(), String> {
-    let is_team_member;
-    match user.is_team_member(client) {
-        Ok(true) => return Ok(()),
-        Ok(false) => {
-            is_team_member = Ok(());
-        }
-        Err(err) => {
-            eprintln!("failed to check team membership: {:?}", err);
-            is_team_member = Err(());
-            // continue on; if we failed to check their membership assume that they are not members.
-        }
+enum ModerationCommand {
+    Block { user: String, patterns: Vec<String> },
+    Grant { user: String, pattern: String },
+}
+
+/// Parses a `@triagebot label-block @user [pattern...]` or `@triagebot label-grant @user
+/// pattern` command out of a comment body.
+fn parse_moderation_command(body: &str, username: &str) -> Option<ModerationCommand> {
+    if let Some(caps) = BLOCK_COMMAND
+        .captures_iter(body)
+        .find(|caps| caps[1].eq_ignore_ascii_case(username))
+    {
+        let patterns = caps[3].split_whitespace().map(String::from).collect();
+        return Some(ModerationCommand::Block {
+            user: caps[2].to_string(),
+            patterns,
+        });
     }
-    if label.starts_with("C-") // categories
-    || label.starts_with("A-") // areas
-    || label.starts_with("E-") // easy, mentor, etc.
-    || label.starts_with("NLL-")
-    || label.starts_with("O-") // operating systems
-    || label.starts_with("S-") // status labels
-    || label.starts_with("T-")
-    || label.starts_with("WG-")
+
+    if let Some(caps) = GRANT_COMMAND
+        .captures_iter(body)
+        .find(|caps| caps[1].eq_ignore_ascii_case(username))
     {
-        return Ok(());
+        return Some(ModerationCommand::Grant {
+            user: caps[2].to_string(),
+            pattern: caps[3].to_string(),
+        });
     }
-    match label {
-        "I-compilemem" | "I-compiletime" | "I-crash" | "I-hang" | "I-ICE" | "I-slow" => {
-            return Ok(());
+
+    None
+}
+
+/// Whether `body` is a `@triagebot labels` discovery request.
+fn is_labels_query(body: &str, username: &str) -> bool {
+    LABELS_QUERY
+        .captures_iter(body)
+        .any(|caps| caps[1].eq_ignore_ascii_case(username))
+}
+
+/// Renders a Markdown summary of `known_labels`, grouped by prefix (`C-`, `A-`, `T-`, ...) and
+/// marked as free-for-all, team-only or not configured per `config`.
+fn labels_help_comment(known_labels: &[String], config: &LabelConfig) -> String {
+    let mut groups: BTreeMap<&str, Vec<&String>> = BTreeMap::new();
+    for label in known_labels {
+        groups.entry(label_prefix(label)).or_default().push(label);
+    }
+
+    let mut out = String::from("## Labels\n\n");
+    for (prefix, labels) in groups {
+        out.push_str(&format!("**{}**\n", prefix));
+        for label in labels {
+            let access = match config.rule_for(label) {
+                LabelRule::Allowed => "anyone".to_string(),
+                LabelRule::TeamOnly(Some(team)) => format!("team-only: {}", team),
+                LabelRule::TeamOnly(None) => "team-only".to_string(),
+                LabelRule::Unrecognized => "not configured".to_string(),
+            };
+            out.push_str(&format!("- `{}` ({})\n", label, access));
         }
-        _ => {}
+        out.push('\n');
+    }
+    out.push_str("Use `label +foo -bar` to add `foo` and remove `bar`.\n");
+    out
+}
+
+/// The grouping prefix for a label, e.g. `C-bug` groups under `C-`.
+fn label_prefix(label: &str) -> &str {
+    match label.find('-') {
+        Some(idx) => &label[..=idx],
+        None => label,
     }
+}
 
-    if is_team_member.is_ok() {
-        Err(format!(
-            "Label {} can only be set by Rust team members",
-            label
-        ))
-    } else {
-        Err(format!(
-            "Label {} can only be set by Rust team members;\
-             we were unable to check if you are a team member.",
-            label
-        ))
+fn check_filter(
+    label: &str,
+    user: &github::User,
+    client: &GithubClient,
+    config: &LabelConfig,
+    moderation: &ModerationState,
+) -> Result<(), HandlerError> {
+    match moderation.verdict(&user.login, label) {
+        ModerationVerdict::Blocked => {
+            return Err(HandlerError::PermissionDenied(format!(
+                "You have been blocked from modifying the {} label.",
+                label
+            )));
+        }
+        ModerationVerdict::Granted => return Ok(()),
+        ModerationVerdict::NoOpinion => {}
+    }
+
+    match config.rule_for(label) {
+        LabelRule::Allowed => return Ok(()),
+        LabelRule::TeamOnly(Some(team)) => {
+            // `GithubClient` has no way to check membership in a single named team, only
+            // overall Rust team membership. Falling back to the blanket check here would let a
+            // member of an unrelated team set a label that `triagebot.toml` scoped to `team`
+            // (e.g. a non-compiler team member setting `T-compiler`), so until a per-team
+            // membership query exists on `GithubClient`, fail closed: nobody can self-serve a
+            // label scoped to a specific team, rather than silently granting it to everyone who
+            // passes the blanket check.
+            return Err(HandlerError::PermissionDenied(
+                config.messages.render_team_scoped_unsupported(label, team),
+            ));
+        }
+        LabelRule::TeamOnly(None) => {}
+        LabelRule::Unrecognized => {
+            return Err(HandlerError::LabelNotFound(label.to_string()));
+        }
+    };
+
+    // A failed membership check is a transient API hiccup, not a definitive answer; retry
+    // rather than silently treating it as "not a member", which could wrongly block a
+    // legitimate team member during a GitHub outage.
+    let is_member = retry::with_retry(
+        || user.is_team_member(client),
+        retry::is_transient_github_error,
+    );
+
+    match is_member {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(HandlerError::PermissionDenied(
+            config.messages.render_team_only(label),
+        )),
+        Err(err) => {
+            eprintln!(
+                "team membership check for {} failed: {:?}",
+                label, err
+            );
+            Err(HandlerError::MembershipCheckFailed(
+                config.messages.render_team_only_check_failed(label),
+            ))
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+//! Per-user/per-label moderation state for `crate::handlers::label`.
+//!
+//! This is the open-to-everyone model's escape hatch: a team member can block a specific user
+//! from touching labels at all, or just a label set they've been abusing (drive-by label
+//! vandalism), and can grant a non-team user the right to set labels in a narrow namespace they
+//! otherwise couldn't touch because of `team-only` rules. `check_filter` consults this before
+//! falling back to the usual prefix/team logic.
+//!
+//! The state is small and changes rarely, so it's persisted as a JSON document committed to the
+//! target repository rather than requiring a database. Since that means the bot writes to the
+//! repository rather than only reading from it, a project must opt in with `moderation-enabled
+//! = true` under `[label]` in `triagebot.toml` (see `crate::config::LabelConfig`) before
+//! `crate::handlers::label::LabelHandler` will act on `label-block`/`label-grant` commands.
+
+use crate::config::pattern_matches;
+use crate::github::GithubClient;
+use failure::Error;
+use std::collections::HashMap;
+
+const MODERATION_FILE: &str = ".triagebot-label-moderation.json";
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ModerationState {
+    /// Users blocked from modifying labels, keyed by login. An empty pattern list means
+    /// "blocked entirely"; otherwise the user is only blocked from the listed label patterns.
+    #[serde(default)]
+    blocks: HashMap<String, Vec<String>>,
+    /// Users granted the right to set labels matching the listed patterns, keyed by login, even
+    /// if those patterns are `team-only` per `triagebot.toml`.
+    #[serde(default)]
+    grants: HashMap<String, Vec<String>>,
+}
+
+pub enum ModerationVerdict {
+    /// Not mentioned by the moderation state; fall through to the normal `check_filter` rules.
+    NoOpinion,
+    /// Blocked from setting this label, explicitly.
+    Blocked,
+    /// Granted the right to set this label, bypassing `team-only`.
+    Granted,
+}
+
+impl ModerationState {
+    pub fn verdict(&self, user: &str, label: &str) -> ModerationVerdict {
+        if let Some(patterns) = self.blocks.get(user) {
+            if patterns.is_empty() || patterns.iter().any(|p| pattern_matches(p, label)) {
+                return ModerationVerdict::Blocked;
+            }
+        }
+        if let Some(patterns) = self.grants.get(user) {
+            if patterns.iter().any(|p| pattern_matches(p, label)) {
+                return ModerationVerdict::Granted;
+            }
+        }
+        ModerationVerdict::NoOpinion
+    }
+
+    /// Blocks `user` from `patterns` (or entirely, if `patterns` is empty), merging with any
+    /// existing block rather than replacing it, like `grant` does. Once a user is fully blocked
+    /// (an empty pattern list), a narrower follow-up call must not quietly widen their access
+    /// back up.
+    pub fn block(&mut self, user: &str, patterns: Vec<String>) {
+        match self.blocks.get_mut(user) {
+            Some(existing) if existing.is_empty() => {
+                // already fully blocked; stay fully blocked
+            }
+            Some(existing) if patterns.is_empty() => {
+                existing.clear();
+            }
+            Some(existing) => {
+                for pattern in patterns {
+                    if !existing.contains(&pattern) {
+                        existing.push(pattern);
+                    }
+                }
+            }
+            None => {
+                self.blocks.insert(user.to_string(), patterns);
+            }
+        }
+    }
+
+    pub fn grant(&mut self, user: &str, pattern: String) {
+        self.grants.entry(user.to_string()).or_default().push(pattern);
+    }
+}
+
+/// Loads the moderation state for `repo`, or an empty one if it has never been written.
+pub fn load(client: &GithubClient, repo: &str) -> Result<ModerationState, Error> {
+    match client.raw_file(repo, MODERATION_FILE)? {
+        Some(contents) => Ok(serde_json::from_slice(&contents)?),
+        None => Ok(ModerationState::default()),
+    }
+}
+
+/// Persists `state` for `repo` so it survives a bot restart.
+pub fn save(client: &GithubClient, repo: &str, state: &ModerationState) -> Result<(), Error> {
+    let contents = serde_json::to_vec_pretty(state)?;
+    client.write_file(repo, MODERATION_FILE, contents, "update label moderation state")
+}
@@ -0,0 +1,44 @@
+//! Bounded exponential-backoff retry for transient GitHub API failures.
+//!
+//! A timeout, a 5xx response, or a secondary rate limit are all hiccups rather than definitive
+//! answers; a naive caller that takes the first error at face value (e.g. "the membership check
+//! failed, so assume they're not a team member") can wrongly deny a legitimate request during a
+//! GitHub outage. Retrying a bounded number of times before giving up avoids that.
+
+use std::thread;
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Calls `f`, retrying with exponential backoff as long as `is_transient` says the error it
+/// produced is worth retrying, up to `MAX_ATTEMPTS` total attempts. Returns the last error once
+/// attempts are exhausted or `is_transient` returns `false`.
+pub fn with_retry<T, E>(
+    mut f: impl FnMut() -> Result<T, E>,
+    is_transient: impl Fn(&E) -> bool,
+) -> Result<T, E> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt + 1 < MAX_ATTEMPTS && is_transient(&err) => {
+                thread::sleep(BASE_DELAY * 2u32.pow(attempt));
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Whether a GitHub API error looks transient (timeout, 502/503/504, secondary rate limit) rather
+/// than a definitive rejection. Other 5xx responses, such as 500, are not treated as transient.
+pub fn is_transient_github_error(err: &failure::Error) -> bool {
+    let msg = err.to_string().to_lowercase();
+    msg.contains("timed out")
+        || msg.contains("timeout")
+        || msg.contains("secondary rate limit")
+        || msg.contains("502")
+        || msg.contains("503")
+        || msg.contains("504")
+}